@@ -1,10 +1,15 @@
 use serde::Deserialize;
 use std::fs;
+use std::hash::Hasher;
+use std::io::Read;
 use std::path::PathBuf;
 use std::path::Path;
+use std::sync::{mpsc, Arc, Mutex};
 
 use clap::Parser;
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use siphasher::sip128::{Hasher128, SipHasher13};
+use threadpool::ThreadPool;
 
 #[derive(Parser)]
 #[command(author, version, about = "Convert ChatGPT all data JSON to Markdown")]
@@ -18,6 +23,27 @@ struct Args {
     /// Output directory for Markdown files
     #[arg(short, long, default_value = ".")]
     outpath: PathBuf,
+
+    /// Embed images as base64 data URLs instead of copying them next to the Markdown file
+    #[arg(long)]
+    inline_images: bool,
+
+    /// Also render each conversation to HTML and emit a searchable index.html
+    #[arg(long)]
+    emit_html: bool,
+
+    /// Export just the main conversation path, or every branch created by edits/regenerations
+    #[arg(long, value_enum, default_value = "main")]
+    branches: BranchMode,
+}
+
+/// Which conversation branches to export — see `--branches`.
+#[derive(Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum BranchMode {
+    /// Follow a single path through the tree (the historical, default behavior).
+    Main,
+    /// Walk every branch created by message edits or regenerated responses.
+    All,
 }
 
 #[derive(Debug, Deserialize)]
@@ -193,8 +219,11 @@ fn sanitize_filename(name: &str) -> String {
         .collect()
 }
 
-/// Search for a PNG matching `file_id` in any `user-*` folder inside `base_folder`.
-fn find_png(file_id: &str, base_folder: &Path) -> Option<PathBuf> {
+/// Image extensions ChatGPT exports attach to conversations.
+const IMAGE_EXTENSIONS: &[&str] = &["png", "jpeg", "jpg", "webp", "gif"];
+
+/// Search for a media file matching `file_id` in any `user-*` folder inside `base_folder`.
+fn find_media(file_id: &str, base_folder: &Path) -> Option<PathBuf> {
     // Iterate over entries in the base folder
     for entry in fs::read_dir(base_folder).ok()? {
         let entry = entry.ok()?;
@@ -204,12 +233,15 @@ fn find_png(file_id: &str, base_folder: &Path) -> Option<PathBuf> {
         if path.is_dir() {
             if let Some(folder_name) = path.file_name().and_then(|f| f.to_str()) {
                 if folder_name.starts_with("user-") {
-                    // Look for the PNG inside this folder
+                    // Look for the matching image inside this folder
                     for file_entry in fs::read_dir(&path).ok()? {
                         let file_entry = file_entry.ok()?;
                         let file_path = file_entry.path();
                         if let Some(fname) = file_path.file_name().and_then(|f| f.to_str()) {
-                            if fname.starts_with(file_id) && fname.ends_with(".png") {
+                            let matches_ext = IMAGE_EXTENSIONS
+                                .iter()
+                                .any(|ext| fname.ends_with(&format!(".{ext}")));
+                            if fname.starts_with(file_id) && matches_ext {
                                 return Some(file_path);
                             }
                         }
@@ -222,56 +254,499 @@ fn find_png(file_id: &str, base_folder: &Path) -> Option<PathBuf> {
     None
 }
 
-fn find_png_for_asset(asset_pointer: &str, user_folder: &Path, out_folder: &Path, figure_base: &Path,) -> Option<String> {
+/// Hash `bytes` with 128-bit SipHash-1-3.
+fn hash128(bytes: &[u8]) -> u128 {
+    let mut hasher = SipHasher13::new();
+    hasher.write(bytes);
+    hasher.finish128().as_u128()
+}
+
+/// Hash the first 4096 bytes of `path` — cheap enough to run on every copied asset.
+fn partial_hash(path: &Path) -> anyhow::Result<u128> {
+    let mut file = fs::File::open(path)?;
+    let mut buf = [0u8; 4096];
+    let n = file.read(&mut buf)?;
+    Ok(hash128(&buf[..n]))
+}
+
+/// Hash the whole file. Only called once a (length, partial hash) bucket collides.
+fn full_hash(path: &Path) -> anyhow::Result<u128> {
+    Ok(hash128(&fs::read(path)?))
+}
+
+/// Number of partial-map shards. Keys are spread across shards by their hash, so
+/// concurrent copies of distinct assets only contend when they land in the same
+/// shard instead of all serializing behind one mutex.
+const DEDUP_SHARDS: usize = 32;
+
+/// Content-addressed dedup of copied image assets, shared across the whole export run.
+///
+/// Identity is confirmed in two stages to avoid hashing large files unnecessarily:
+/// a cheap partial hash over the first 4KB block (bucketed together with file length)
+/// first, and only on a bucket collision a full-file hash to confirm the files are
+/// byte-identical. The partial map is sharded (see `DEDUP_SHARDS`) so holding a shard's
+/// lock across a copy only blocks workers whose asset happens to land in that shard.
+struct AssetDedup {
+    partial: Vec<Mutex<HashMap<(u64, u128), PathBuf>>>,
+    full: Mutex<HashMap<u128, PathBuf>>,
+}
+
+impl Default for AssetDedup {
+    fn default() -> Self {
+        Self {
+            partial: (0..DEDUP_SHARDS).map(|_| Mutex::new(HashMap::new())).collect(),
+            full: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl AssetDedup {
+    /// The (length, partial hash) key is already a SipHash output, so its low bits
+    /// are an even enough spread to pick a shard from.
+    fn shard(&self, key: &(u64, u128)) -> &Mutex<HashMap<(u64, u128), PathBuf>> {
+        &self.partial[(key.1 as usize) % self.partial.len()]
+    }
+
+    /// Copy `src` to `dest`, unless an identical file was already copied earlier in
+    /// this run — in which case the path of that existing copy is returned and `src`
+    /// is not copied.
+    fn copy_deduped(&self, src: &Path, dest: &Path) -> anyhow::Result<PathBuf> {
+        let len = fs::metadata(src)?.len();
+        let key = (len, partial_hash(src)?);
+
+        // Hold this key's shard lock across the first copy for its bucket, so a
+        // concurrent worker that hits the same (length, partial hash) key blocks
+        // until the file actually exists on disk instead of hashing a copy still in
+        // flight (or not yet started) — other shards, and so most other assets,
+        // stay uncontended.
+        let mut partial_map = self.shard(&key).lock().unwrap();
+        let candidate = partial_map.get(&key).cloned();
+
+        if candidate.is_none() {
+            fs::copy(src, dest)?;
+            partial_map.insert(key, dest.to_path_buf());
+            return Ok(dest.to_path_buf());
+        }
+        drop(partial_map);
+        let candidate = candidate.unwrap();
+
+        let full = full_hash(src)?;
+        let mut full_map = self.full.lock().unwrap();
+        if let Some(existing) = full_map.get(&full) {
+            return Ok(existing.clone());
+        }
+        if full_hash(&candidate)? == full {
+            full_map.insert(full, candidate.clone());
+            return Ok(candidate);
+        }
+        // Partial hash and length collided but the full contents differ; copy as a distinct file.
+        fs::copy(src, dest)?;
+        full_map.insert(full, dest.to_path_buf());
+        Ok(dest.to_path_buf())
+    }
+}
+
+/// Base64-encode `path`'s contents into a `data:{mime};base64,...` URL.
+fn to_data_url(path: &Path) -> anyhow::Result<String> {
+    use base64::Engine;
+    let bytes = fs::read(path)?;
+    let mime = mime_guess::from_path(path)
+        .first_or_octet_stream()
+        .to_string();
+    let encoded = base64::engine::general_purpose::STANDARD.encode(bytes);
+    Ok(format!("data:{mime};base64,{encoded}"))
+}
+
+/// Resolve an asset pointer (e.g. `sediment://file-...`) to a Markdown image reference.
+///
+/// In the default mode the source image is copied next to the Markdown file and a
+/// relative link is returned. With `inline` set, the image is embedded directly as a
+/// base64 data URL so the Markdown file is self-contained.
+fn resolve_image_asset(
+    asset_pointer: &str,
+    user_folder: &Path,
+    out_folder: &Path,
+    figure_base: &Path,
+    inline: bool,
+    dedup: &AssetDedup,
+) -> Option<String> {
     // Extract the file ID after "sediment://"
     let file_id = asset_pointer.strip_prefix("sediment://")?;
+    let src = find_media(file_id, user_folder)?;
+
+    if inline {
+        return match to_data_url(&src) {
+            Ok(data_url) => Some(data_url),
+            Err(e) => {
+                eprintln!("Failed to inline {}: {}", src.display(), e);
+                None
+            }
+        };
+    }
 
-    if let Some(png_src) = find_png( file_id, user_folder ){
-        // Create new filename matching the Markdown file
-        let asset_stem = Path::new(&png_src).file_stem()?.to_string_lossy();
-        let new_png_path = out_folder.join(format!(
-            "{}_{}.png",
-            figure_base.display(),
-            asset_stem
-        ));
-        // Copy the PNG
-        println!("I am copying {} to {} - right?", &png_src.display(), &new_png_path.display());
-        if let Err(e) = fs::copy(Path::new(&png_src), &new_png_path) {
+    // Create new filename matching the Markdown file, keeping the source extension
+    let asset_stem = src.file_stem()?.to_string_lossy();
+    let ext = src.extension().and_then(|e| e.to_str()).unwrap_or("png");
+    let new_path = out_folder.join(format!("{}_{}.{}", figure_base.display(), asset_stem, ext));
+    let final_path = match dedup.copy_deduped(&src, &new_path) {
+        Ok(path) => path,
+        Err(e) => {
             eprintln!(
                 "Failed to copy {} to {}: {}",
-                png_src.display(),
-                new_png_path.display(),
+                src.display(),
+                new_path.display(),
                 e
             );
             return None;
         }
-        // Return just the filename for Markdown
-        Some(new_png_path.file_name()?.to_string_lossy().to_string())
-    }else {
+    };
+    // Return just the filename for Markdown
+    Some(final_path.file_name()?.to_string_lossy().to_string())
+}
+
+/// Render one message to its `**role:**\n\ncontent\n\n---\n\n` Markdown block. Shared
+/// by the main-path walk and the all-branches traversal so both render identically.
+fn render_message_block(
+    msg: &Message,
+    parent_folder: &Path,
+    outpath: &Path,
+    figure_base: &Path,
+    inline_images: bool,
+    dedup: &AssetDedup,
+) -> String {
+    let role = match msg.author.role.as_str() {
+        "user" => "👤 User",
+        "assistant" => "🤖 Assistant",
+        other => other,
+    };
+
+    let content = match &msg.content {
+        Content::Text { parts } => parts
+            .iter()
+            .map(|p| match p {
+                Part::Text(s) => s.clone(),
+                Part::Image { asset_pointer, .. } => match asset_pointer {
+                    Some(ptr) => match resolve_image_asset(ptr, parent_folder, outpath, figure_base, inline_images, dedup) {
+                        Some(image) => format!("![]({})", image),
+                        None => format!("![Missing image for {}]", ptr),
+                    },
+                    None => "![Image with no asset pointer]".to_string(),
+                },
+            })
+            .collect::<Vec<_>>()
+            .join("\n"),
+
+        Content::MultimodalText { parts } => parts
+            .iter()
+            .map(|p|
+                match p { MultimodalPart::ImageAssetPointer { asset_pointer, .. } => {
+                    match resolve_image_asset(asset_pointer, parent_folder, outpath, figure_base, inline_images, dedup) {
+                        Some(image) => format!("![]({})", image),
+                        None => format!("![Missing image for {}]", asset_pointer),
+                    }
+                }
+                // handle other MultimodalPart types here if you have them
+            })
+            .collect::<Vec<_>>()
+            .join("\n\n"),
+
+        Content::Thoughts { thoughts, .. } => thoughts
+            .iter()
+            .map(|t| format!("**{}**\n{}", t.summary, t.content))
+            .collect::<Vec<_>>()
+            .join("\n\n"),
+        Content::Code { language, text, .. } => {
+            let lang = language.as_deref().unwrap_or("text");
+            format!("```{}\n{}\n```", lang, text)
+        },
+        Content::ReasoningRecap { content, content_references } => {
+            let mut md = content.clone();
+            if let Some(refs) = content_references {
+                for r in refs {
+                    if !r.safe_urls.is_empty() {
+                        md.push_str(&format!("\n[Reference]({})", r.safe_urls[0]));
+                    }
+                }
+            }
+            md
+        },
+    };
+
+    format!("**{}:**\n\n{}\n\n---\n\n", role, content)
+}
+
+/// Depth-first collection of every root-to-leaf path through `messages_map`. A node
+/// with more than one child produces one path per child, so edited/regenerated
+/// messages all get their own branch instead of only the last one.
+fn collect_branch_paths(messages_map: &HashMap<String, MessageEntry>, root_id: &str) -> Vec<Vec<String>> {
+    if root_id.is_empty() {
+        return Vec::new();
+    }
+
+    let mut paths = Vec::new();
+    let mut path: Vec<String> = vec![root_id.to_string()];
+    // Ids currently on the path being explored, not every id ever seen — lets us
+    // detect a cycle in (malformed) `mapping` data without rejecting the legitimate
+    // case of distinct branches sharing no ancestry.
+    let mut on_path: HashSet<String> = HashSet::from([root_id.to_string()]);
+    // Explicit stack instead of recursion: a long, single-branch conversation would
+    // otherwise recurse once per message and can exhaust the thread stack.
+    let mut stack: Vec<(String, usize)> = vec![(root_id.to_string(), 0)];
+
+    while let Some((id, next_child)) = stack.pop() {
+        let children = messages_map.get(&id).map(|e| e.children.as_slice()).unwrap_or(&[]);
+
+        if next_child >= children.len() {
+            if next_child == 0 {
+                // Leaf: never had a child to descend into.
+                paths.push(path.clone());
+            }
+            path.pop();
+            on_path.remove(&id);
+            continue;
+        }
+
+        // Resume this node at its next child once the current one is fully explored.
+        stack.push((id.clone(), next_child + 1));
+
+        let child = &children[next_child];
+        if on_path.contains(child) {
+            // Cycle — don't recurse back into an ancestor.
+            continue;
+        }
+        path.push(child.clone());
+        on_path.insert(child.clone());
+        stack.push((child.clone(), 0));
+    }
+
+    paths
+}
+
+/// Markdown note to insert right after a message whose entry has more than one
+/// child, so a reader can tell where the conversation forked — in both the default
+/// single-path export and each `--branches all` branch file.
+fn fork_annotation(entry: &MessageEntry) -> Option<String> {
+    if entry.children.len() > 1 {
+        Some(format!(
+            "_[conversation forks here into {} branches]_\n\n",
+            entry.children.len()
+        ))
+    } else {
         None
     }
 }
 
+/// Render a single branch (root-to-leaf path) to Markdown, annotating the message
+/// where the conversation forked into multiple children.
+fn render_branch_md(
+    path: &[String],
+    messages_map: &HashMap<String, MessageEntry>,
+    header: &str,
+    parent_folder: &Path,
+    outpath: &Path,
+    figure_base: &Path,
+    inline_images: bool,
+    dedup: &AssetDedup,
+) -> (String, usize) {
+    let mut md = header.to_string();
+    let mut sections = 0;
+    for id in path {
+        if let Some(entry) = messages_map.get(id) {
+            if let Some(msg) = &entry.message {
+                md.push_str(&render_message_block(msg, parent_folder, outpath, figure_base, inline_images, dedup));
+                sections += 1;
+            }
+            if let Some(annotation) = fork_annotation(entry) {
+                md.push_str(&annotation);
+            }
+        }
+    }
+    (md, sections)
+}
 
 
 
-fn main() -> anyhow::Result<()> {
-    let args = Args::parse();
 
-    // Read JSON
-    let data = fs::read_to_string(&args.infile)?;
-    let parent_folder = args.infile.parent().unwrap_or_else(|| Path::new(".")).to_path_buf();
-    let conversations: Vec<Conversation> = serde_json::from_str(&data)?;
+/// Outcome of exporting a single conversation, sent back from a worker thread.
+struct ConvResult {
+    title: String,
+    written: bool,
+    index_entry: Option<IndexEntry>,
+}
 
-    fs::create_dir_all(&args.outpath)?;
+/// One row of the HTML site index / `search-index.json`, built when `--emit-html` is set.
+#[derive(serde::Serialize)]
+struct IndexEntry {
+    id: String,
+    title: String,
+    created: String,
+    html_file: String,
+    tokens: Vec<String>,
+}
 
-    for conv in conversations {
+/// Strip `![alt](target)` Markdown image references from `s`. Used before tokenizing
+/// for the search index, since `target` is a full `data:{mime};base64,...` payload
+/// under `--inline-images` and would otherwise get chopped into bogus "tokens" that
+/// bloat `search-index.json` with no search value.
+fn strip_image_markdown(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut i = 0;
+    while i < s.len() {
+        if let Some(rest) = s[i..].strip_prefix("![") {
+            if let Some(close_bracket) = rest.find(']') {
+                let after_bracket = i + 2 + close_bracket + 1;
+                if let Some(paren_rest) = s.get(after_bracket..).and_then(|r| r.strip_prefix('(')) {
+                    if let Some(close_paren) = paren_rest.find(')') {
+                        i = after_bracket + 1 + close_paren + 1;
+                        continue;
+                    }
+                }
+            }
+        }
+        let ch = s[i..].chars().next().unwrap();
+        out.push(ch);
+        i += ch.len_utf8();
+    }
+    out
+}
+
+/// Lowercase, de-duplicated bag of words longer than two characters, used for the
+/// client-side search index. No stemming — the browser-side search does prefix and
+/// substring matching, so exact tokens are enough.
+fn tokenize(text: &str) -> Vec<String> {
+    let text = strip_image_markdown(text);
+    let mut tokens: BTreeSet<String> = BTreeSet::new();
+    for word in text.split(|c: char| !c.is_alphanumeric()) {
+        if word.chars().count() > 2 {
+            tokens.insert(word.to_lowercase());
+        }
+    }
+    tokens.into_iter().collect()
+}
+
+/// Escape the five HTML special characters. Used for any conversation-provided text
+/// (titles, timestamps) interpolated directly into generated HTML, since a title like
+/// "Compare <div> vs <span>" would otherwise corrupt the page or inject markup.
+fn escape_html(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// Render `md` to a standalone HTML document, with the same minimal doctype/charset
+/// boilerplate as `index.html` — conversations are full of emoji and other non-ASCII
+/// text, and a bare `pulldown_cmark` fragment leaves the encoding to the browser's guess.
+fn render_conversation_html(title: &str, md: &str) -> String {
+    let parser = pulldown_cmark::Parser::new(md);
+    let mut body = String::new();
+    pulldown_cmark::html::push_html(&mut body, parser);
+    let title = escape_html(title);
+
+    format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<title>{title}</title>
+</head>
+<body>
+{body}
+</body>
+</html>
+"#
+    )
+}
+
+/// Write `index.html`, `search-index.json` and the small search script that powers it.
+fn render_site_index(outpath: &Path, entries: &[IndexEntry]) -> anyhow::Result<()> {
+    fs::write(outpath.join("search-index.json"), serde_json::to_string(entries)?)?;
+
+    let rows = entries
+        .iter()
+        .map(|e| {
+            format!(
+                "<li data-id=\"{}\"><a href=\"{}\">{}</a> <span class=\"created\">{}</span></li>",
+                escape_html(&e.id), e.html_file, escape_html(&e.title), escape_html(&e.created)
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    let html = format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<title>Conversation archive</title>
+</head>
+<body>
+<h1>Conversation archive</h1>
+<input type="search" id="search" placeholder="Search conversations...">
+<ul id="results">
+{rows}
+</ul>
+<script src="search.js"></script>
+</body>
+</html>
+"#
+    );
+    fs::write(outpath.join("index.html"), html)?;
+
+    fs::write(outpath.join("search.js"), SEARCH_JS)?;
+    Ok(())
+}
+
+/// Client-side prefix/substring search over `search-index.json`. No server required.
+const SEARCH_JS: &str = r#"
+fetch("search-index.json")
+  .then((res) => res.json())
+  .then((entries) => {
+    const input = document.getElementById("search");
+    const list = document.getElementById("results");
+    const items = Array.from(list.children);
+
+    input.addEventListener("input", () => {
+      const query = input.value.trim().toLowerCase();
+      items.forEach((item) => {
+        const entry = entries.find((e) => e.id === item.dataset.id);
+        if (!entry) return;
+        const hit =
+          query === "" ||
+          entry.title.toLowerCase().includes(query) ||
+          entry.tokens.some((t) => t.startsWith(query) || t.includes(query));
+        item.style.display = hit ? "" : "none";
+      });
+    });
+  });
+"#;
+
+/// Render one conversation to its Markdown file. Runs on a worker thread, so it
+/// must not touch anything outside its own `outpath`/`parent_folder` inputs.
+fn process_conversation(
+    conv: Conversation,
+    outpath: &Path,
+    parent_folder: &Path,
+    inline_images: bool,
+    emit_html: bool,
+    branches: BranchMode,
+    dedup: &AssetDedup,
+) -> anyhow::Result<ConvResult> {
+        let conv_id = conv.id.clone();
         let title = conv.title.clone().unwrap_or_else(|| "untitled".to_string());
         if title == "New chat"{
-            continue;
+            return Ok(ConvResult { title, written: false, index_entry: None });
         }
         let safe_title = sanitize_filename(&title);
-        
+
 
         let mut md = String::new();
         md.push_str(&format!("# {}\n\n", title));
@@ -285,9 +760,14 @@ fn main() -> anyhow::Result<()> {
         }else {
             "unkown".to_string()
         };
+        let header = md.clone();
         let save_time = sanitize_filename(&time);
-        let filename = args.outpath.join(format!("conversation_{}_{}.md", save_time,safe_title));
-        let figure_base = args.outpath.join(format!("conversation_{}_{}", save_time,safe_title));
+        // `conv.id` is unique per conversation, so folding it in guarantees distinct
+        // output paths even when two conversations share a title and creation time —
+        // each conversation's worker thread then only ever writes its own file.
+        let safe_id = sanitize_filename(&conv_id);
+        let filename = outpath.join(format!("conversation_{}_{}_{}.md", save_time, safe_title, safe_id));
+        let figure_base = outpath.join(format!("conversation_{}_{}_{}", save_time, safe_title, safe_id));
         let mut sections = 0;
 
         if let Some(mapping) = conv.mapping.as_object() {
@@ -312,62 +792,14 @@ fn main() -> anyhow::Result<()> {
             println!("I got the root id '{}'", root_id );
             // Only traverse if root exists
             if !root_id.is_empty() {
-                let mut id = &root_id;  
+                let mut id = &root_id;
                 while let Some(entry) = messages_map.get(id) {
                     if let Some(msg) = &entry.message {
-                        let role = match msg.author.role.as_str() {
-                            "user" => "👤 User",
-                            "assistant" => "🤖 Assistant",
-                            other => other,
-                        };
-
-                        let content = match &msg.content {
-                            Content::Text { parts } => parts
-                                .iter()
-                                .filter_map(|p| match p { Part::Text(s) => Some(s.as_str()), &Part::Image { .. } => todo!() })
-                                .collect::<Vec<_>>()
-                                .join("\n"),
-
-                            Content::MultimodalText { parts } => parts
-                                .iter()
-                                .map(|p| 
-                                    match p { MultimodalPart::ImageAssetPointer { asset_pointer, .. } => {
-                                        // Convert asset_pointer to a PNG path
-                                        // Assuming you have a function like `find_png_for_asset`
-                                        match find_png_for_asset(asset_pointer, &parent_folder, &args.outpath, &figure_base ) {
-                                            Some(png_path) => format!("![]({})", png_path),
-                                            None => format!("![Missing image for {}]", asset_pointer),
-                                        }
-                                    }
-                                    // handle other MultimodalPart types here if you have them
-                                })
-                                .collect::<Vec<_>>()
-                                .join("\n\n"),
-
-                            Content::Thoughts { thoughts, .. } => thoughts
-                                .iter()
-                                .map(|t| format!("**{}**\n{}", t.summary, t.content))
-                                .collect::<Vec<_>>()
-                                .join("\n\n"),
-                            Content::Code { language, text, .. } => {
-                                let lang = language.as_deref().unwrap_or("text");
-                                format!("```{}\n{}\n```", lang, text)
-                            },
-                            Content::ReasoningRecap { content, content_references } => {
-                                let mut md = content.clone();
-                                if let Some(refs) = content_references {
-                                    for r in refs {
-                                        if !r.safe_urls.is_empty() {
-                                            md.push_str(&format!("\n[Reference]({})", r.safe_urls[0]));
-                                        }
-                                    }
-                                }
-                                md
-                            },
-                        };
-
+                        md.push_str(&render_message_block(msg, parent_folder, outpath, &figure_base, inline_images, dedup));
                         sections += 1;
-                        md.push_str(&format!("**{}:**\n\n{}\n\n---\n\n", role, content));
+                    }
+                    if let Some(annotation) = fork_annotation(entry) {
+                        md.push_str(&annotation);
                     }
 
                     //if let Some(first_child) = entry.children.first() {
@@ -383,6 +815,47 @@ fn main() -> anyhow::Result<()> {
                 if sections == 0 {
                     println!("But I could not identify the entry!");
                 }
+
+                if sections > 0 && branches == BranchMode::All {
+                    let main_path = {
+                        let mut path = Vec::new();
+                        let mut id = &root_id;
+                        while let Some(entry) = messages_map.get(id) {
+                            path.push(id.clone());
+                            match entry.children.last() {
+                                Some(next) => id = next,
+                                None => break,
+                            }
+                        }
+                        path
+                    };
+
+                    let mut branch_no = 2;
+                    for path in collect_branch_paths(&messages_map, &root_id) {
+                        if path == main_path {
+                            continue;
+                        }
+                        let (branch_md, branch_sections) = render_branch_md(
+                            &path,
+                            &messages_map,
+                            &header,
+                            parent_folder,
+                            outpath,
+                            &figure_base,
+                            inline_images,
+                            dedup,
+                        );
+                        if branch_sections > 0 {
+                            let branch_filename = outpath.join(format!(
+                                "conversation_{}_{}_{}_branch{}.md",
+                                save_time, safe_title, safe_id, branch_no
+                            ));
+                            fs::write(&branch_filename, branch_md)?;
+                            println!("Wrote {}", branch_filename.display());
+                        }
+                        branch_no += 1;
+                    }
+                }
             } else {
                 // root missing, skip this entry
                 println!("No root message found — skipping this entry");
@@ -413,15 +886,90 @@ fn main() -> anyhow::Result<()> {
             }
         }*/
         if sections > 0 {
-            fs::write(&filename, md)?;
+            fs::write(&filename, &md)?;
             println!("Wrote {}", filename.display());
+
+            let index_entry = if emit_html {
+                let html = render_conversation_html(&title, &md);
+                let html_path = filename.with_extension("html");
+                fs::write(&html_path, html)?;
+
+                Some(IndexEntry {
+                    id: conv_id,
+                    title: title.clone(),
+                    created: time,
+                    html_file: html_path.file_name().unwrap().to_string_lossy().to_string(),
+                    tokens: tokenize(&md),
+                })
+            } else {
+                None
+            };
+
+            Ok(ConvResult { title, written: true, index_entry })
         }else {
             println!("ERROR: Failed to detect content for '{}' - file {}", title, filename.display() );
+            Ok(ConvResult { title, written: false, index_entry: None })
+        }
+}
+
+fn main() -> anyhow::Result<()> {
+    let args = Args::parse();
+
+    // Read JSON
+    let data = fs::read_to_string(&args.infile)?;
+    let parent_folder = args.infile.parent().unwrap_or_else(|| Path::new(".")).to_path_buf();
+    let conversations: Vec<Conversation> = serde_json::from_str(&data)?;
+
+    fs::create_dir_all(&args.outpath)?;
+
+    let pool = ThreadPool::new(num_cpus::get());
+    let (tx, rx) = mpsc::channel::<anyhow::Result<ConvResult>>();
+    let total = conversations.len();
+    let dedup = Arc::new(AssetDedup::default());
+
+    for conv in conversations {
+        let tx = tx.clone();
+        let outpath = args.outpath.clone();
+        let parent_folder = parent_folder.clone();
+        let inline_images = args.inline_images;
+        let emit_html = args.emit_html;
+        let branches = args.branches;
+        let dedup = Arc::clone(&dedup);
+        pool.execute(move || {
+            let result = process_conversation(conv, &outpath, &parent_folder, inline_images, emit_html, branches, &dedup);
+            // The receiver outlives every worker, so this only fails if it already hung up.
+            let _ = tx.send(result);
+        });
+    }
+    drop(tx);
+
+    let mut written = 0;
+    let mut skipped = 0;
+    let mut failed = 0;
+    let mut index_entries = Vec::new();
+    for result in rx {
+        match result {
+            Ok(ConvResult { written: true, index_entry, .. }) => {
+                written += 1;
+                index_entries.extend(index_entry);
+            }
+            Ok(ConvResult { written: false, .. }) => skipped += 1,
+            Err(e) => {
+                eprintln!("Failed to export conversation: {e}");
+                failed += 1;
+            }
         }
-        
-        
-        
     }
+    pool.join();
+
+    if args.emit_html {
+        render_site_index(&args.outpath, &index_entries)?;
+        println!("Wrote {}", args.outpath.join("index.html").display());
+    }
+
+    println!(
+        "Done: {written} written, {skipped} skipped, {failed} failed (of {total} conversations)"
+    );
 
     Ok(())
 }